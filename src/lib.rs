@@ -1,14 +1,28 @@
 use anyhow::anyhow;
+use arrow::array::{
+    ArrayRef, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array,
+    StringArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::FileWriter as IpcWriter;
+use arrow::record_batch::RecordBatch;
 use clap::Clap;
 use csv;
 use hdf5::types::*;
 use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
 use log::info;
+use parquet::arrow::ArrowWriter;
 use pyo3::prelude::*;
 use pyo3::types::PyString;
 use serde::Serialize;
 use sprs::CsMatBase;
+use std::fs::File;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+/// row-block size used when writing Parquet/IPC from the Python entry point, which has no
+/// `--block-size` CLI flag of its own; matches the CLI's own default block size
+const DEFAULT_ARROW_BLOCK_SIZE: usize = 100_000;
 
 #[pymodule]
 fn counts_to_csv(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
@@ -18,6 +32,14 @@ fn counts_to_csv(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
         delimiter: &PyString,
         column_orient: &PyString,
         outfile: &PyString,
+        format: &PyString,
+        quote: &PyString,
+        quote_style: &PyString,
+        escape_style: &PyString,
+        escape: &PyString,
+        line_terminator: &PyString,
+        obs_cols: &PyString,
+        var_cols: &PyString,
     ) -> PyResult<()> {
         use pyo3::exceptions::PyException;
         let outfile_path = PathBuf::from(outfile.to_str()?);
@@ -46,10 +68,82 @@ fn counts_to_csv(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
             };
             x.unwrap()
         };
+        let out_format = {
+            let x = match format.to_str()? {
+                "csv" => Ok(OutputFormat::Csv),
+                "parquet" => Ok(OutputFormat::Parquet),
+                "ipc" => Ok(OutputFormat::Ipc),
+                "triplet" => Ok(OutputFormat::Triplet),
+                "mtx" => Ok(OutputFormat::Mtx),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid value: {}\nPossible values: csv, parquet, ipc, triplet, mtx",
+                    format
+                )),
+            };
+            x.unwrap()
+        };
+        let single_byte = |s: &PyString| -> anyhow::Result<u8> {
+            let s = s.to_str()?;
+            if s.len() == 1 {
+                Ok(s.as_bytes()[0])
+            } else {
+                Err(anyhow::anyhow!(
+                    "Invalid value: {}\nExpected a single character",
+                    s
+                ))
+            }
+        };
+        let quote_byte = single_byte(quote).unwrap();
+        let escape_byte = single_byte(escape).unwrap();
+        let quote_style_arg = {
+            let x = match quote_style.to_str()? {
+                "always" => Ok(QuoteStyleArg::Always),
+                "necessary" => Ok(QuoteStyleArg::Necessary),
+                "non-numeric" => Ok(QuoteStyleArg::NonNumeric),
+                "never" => Ok(QuoteStyleArg::Never),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid value: {}\nPossible values: always, necessary, non-numeric, never",
+                    quote_style
+                )),
+            };
+            x.unwrap()
+        };
+        let escape_style_arg = {
+            let x = match escape_style.to_str()? {
+                "double-quote" => Ok(EscapeStyle::DoubleQuote),
+                "backslash" => Ok(EscapeStyle::Backslash),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid value: {}\nPossible values: double-quote, backslash",
+                    escape_style
+                )),
+            };
+            x.unwrap()
+        };
+        let terminator_arg = {
+            let x = match line_terminator.to_str()? {
+                "unix" => Ok(LineTerminator::Unix),
+                "windows" => Ok(LineTerminator::Windows),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid value: {}\nPossible values: unix, windows",
+                    line_terminator
+                )),
+            };
+            x.unwrap()
+        };
         let libopts = LibOpts {
             column_orient: orient,
-            delimiter: delim,
             outfile: outfile_path,
+            format: out_format,
+            csv: CsvOpts {
+                delimiter: delim,
+                quote: quote_byte,
+                quote_style: quote_style_arg,
+                escape_style: escape_style_arg,
+                escape: escape_byte,
+                terminator: terminator_arg,
+            },
+            obs_cols: parse_cols(obs_cols.to_str()?),
+            var_cols: parse_cols(var_cols.to_str()?),
         };
         make_csv(adata, libopts).map_err(|err| PyException::new_err(err.to_string()))
     }
@@ -60,12 +154,15 @@ fn counts_to_csv(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
 // for argument parsing
 struct LibOpts {
     column_orient: Orient,
-    delimiter: Delimiter,
     outfile: PathBuf,
+    format: OutputFormat,
+    csv: CsvOpts,
+    obs_cols: Vec<String>,
+    var_cols: Vec<String>,
 }
 
 /// argument enum for delimiter
-#[derive(Clap)]
+#[derive(Clap, Clone, Copy)]
 pub(crate) enum Delimiter {
     Comma,
     Tab,
@@ -75,20 +172,155 @@ pub(crate) enum Delimiter {
 }
 
 /// argument enum for column_orient
-#[derive(Clap)]
+#[derive(Clap, Clone, Copy)]
 pub(crate) enum Orient {
     VarNames,
     ObsNames,
 }
 
+/// argument enum for output file format
+#[derive(Clap, Clone, Copy)]
+pub(crate) enum OutputFormat {
+    Csv,
+    Parquet,
+    Ipc,
+    /// long-format CSV of row_name/col_name/value, one record per nonzero entry
+    Triplet,
+    /// MatrixMarket coordinate file plus row-names and col-names sidecar files, one record per
+    /// nonzero entry
+    Mtx,
+}
+
+/// argument enum for when fields get wrapped in quote characters
+#[derive(Clap, Clone, Copy)]
+pub(crate) enum QuoteStyleArg {
+    Always,
+    Necessary,
+    NonNumeric,
+    Never,
+}
+
+/// argument enum for how a quote character embedded in a field is escaped
+#[derive(Clap, Clone, Copy)]
+pub(crate) enum EscapeStyle {
+    DoubleQuote,
+    Backslash,
+}
+
+/// argument enum for the CSV line terminator
+#[derive(Clap, Clone, Copy)]
+pub(crate) enum LineTerminator {
+    Unix,
+    Windows,
+}
+
+/// CSV formatting options shared by the CLI and Python entry points
+#[derive(Clone, Copy)]
+pub(crate) struct CsvOpts {
+    pub(crate) delimiter: Delimiter,
+    pub(crate) quote: u8,
+    pub(crate) quote_style: QuoteStyleArg,
+    pub(crate) escape_style: EscapeStyle,
+    pub(crate) escape: u8,
+    pub(crate) terminator: LineTerminator,
+}
+
+/// build a csv::WriterBuilder configured from CsvOpts
+pub(crate) fn build_csv_writer_builder(opts: CsvOpts) -> csv::WriterBuilder {
+    let delimiter = match opts.delimiter {
+        Delimiter::Comma => b',',
+        Delimiter::Tab => b'\t',
+        Delimiter::Colon => b':',
+        Delimiter::Pipe => b'|',
+        Delimiter::Semicolon => b';',
+    };
+    let quote_style = match opts.quote_style {
+        QuoteStyleArg::Always => csv::QuoteStyle::Always,
+        QuoteStyleArg::Necessary => csv::QuoteStyle::Necessary,
+        QuoteStyleArg::NonNumeric => csv::QuoteStyle::NonNumeric,
+        QuoteStyleArg::Never => csv::QuoteStyle::Never,
+    };
+    let terminator = match opts.terminator {
+        LineTerminator::Unix => csv::Terminator::Any(b'\n'),
+        LineTerminator::Windows => csv::Terminator::CRLF,
+    };
+
+    let mut builder = csv::WriterBuilder::new();
+    builder
+        .has_headers(false)
+        .delimiter(delimiter)
+        .quote(opts.quote)
+        .quote_style(quote_style)
+        .terminator(terminator);
+    match opts.escape_style {
+        EscapeStyle::DoubleQuote => {
+            builder.double_quote(true);
+        }
+        EscapeStyle::Backslash => {
+            builder.double_quote(false).escape(opts.escape);
+        }
+    };
+
+    builder
+}
+
+/// maps an ArrayDtype to the Arrow primitive type and array used to store a column of it, and to
+/// the MatrixMarket field keyword used to describe it
+pub(crate) trait ToArrowColumn {
+    fn arrow_data_type() -> DataType;
+    fn to_arrow_array(column: Vec<Self>) -> ArrayRef
+    where
+        Self: Sized;
+    fn mtx_field() -> &'static str;
+}
+
+/// implements ToArrowColumn for a numeric type by delegating to its Arrow array's `From<Vec<_>>`
+macro_rules! impl_to_arrow_column {
+    ($t:ty, $array:ty, $data_type:expr, $mtx_field:expr) => {
+        impl ToArrowColumn for $t {
+            fn arrow_data_type() -> DataType {
+                $data_type
+            }
+
+            fn to_arrow_array(column: Vec<Self>) -> ArrayRef {
+                Arc::new(<$array>::from(column))
+            }
+
+            fn mtx_field() -> &'static str {
+                $mtx_field
+            }
+        }
+    };
+}
+
+impl_to_arrow_column!(i8, Int8Array, DataType::Int8, "integer");
+impl_to_arrow_column!(i16, Int16Array, DataType::Int16, "integer");
+impl_to_arrow_column!(i32, Int32Array, DataType::Int32, "integer");
+impl_to_arrow_column!(i64, Int64Array, DataType::Int64, "integer");
+impl_to_arrow_column!(u8, UInt8Array, DataType::UInt8, "integer");
+impl_to_arrow_column!(u16, UInt16Array, DataType::UInt16, "integer");
+impl_to_arrow_column!(u32, UInt32Array, DataType::UInt32, "integer");
+impl_to_arrow_column!(u64, UInt64Array, DataType::UInt64, "integer");
+impl_to_arrow_column!(f32, Float32Array, DataType::Float32, "real");
+impl_to_arrow_column!(f64, Float64Array, DataType::Float64, "real");
+
 /// trait that describes the type for the data array
-pub(crate) trait ArrayDtype: H5Type + Default + Copy + Serialize {}
-impl<T> ArrayDtype for T where T: H5Type + Default + Copy + Serialize {}
+pub(crate) trait ArrayDtype:
+    H5Type + Default + Copy + Serialize + ToArrowColumn + std::fmt::Display
+{
+}
+impl<T> ArrayDtype for T where
+    T: H5Type + Default + Copy + Serialize + ToArrowColumn + std::fmt::Display
+{
+}
 
-/// represents a row to be written to the CSV file
+/// represents a row to be written to the CSV file, with any requested row-axis annotation
+/// columns (e.g. obs columns joined in when obs are rows) serialized between the row name and
+/// the counts values
 #[derive(Serialize)]
 pub(crate) struct Row<'a, T: ArrayDtype> {
     pub(crate) name: &'a str,
+    pub(crate) annotations: &'a [String],
     pub(crate) values: RowValIter<'a, T>,
 }
 
@@ -165,8 +397,381 @@ pub(crate) fn create_progress_bar(iter_size: usize) -> ProgressBar {
     bar
 }
 
-/// write counts matrix to csv
+/// parse a comma-separated `--obs-cols`/`--var-cols` argument into the requested column names
+pub(crate) fn parse_cols(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// rebase the sub-slice of `indptr` covering rows `[r0, r1)` so it starts at zero, for
+/// constructing a block's own CsMatBase from a hyperslab read of `X/data`/`X/indices`
+pub(crate) fn rebase_indptr(indptr: &[usize], r0: usize, r1: usize) -> Vec<usize> {
+    let lo = indptr[r0];
+    indptr[r0..=r1].iter().map(|p| p - lo).collect()
+}
+
+/// read an `obs/<col>` or `var/<col>` annotation dataset from the HDF5 file, stringifying
+/// numeric dtypes so string and numeric annotation columns can be joined the same way. Errors if
+/// the column's length doesn't match `expected_len` (the axis it's being joined against), so a
+/// short dataset can't silently produce ragged CSV rows
+pub(crate) fn read_annotation_column(
+    file: &hdf5::File,
+    group: &str,
+    col: &str,
+    expected_len: usize,
+) -> anyhow::Result<Vec<String>> {
+    let path = format!("{}/{}", group, col);
+
+    // pandas categorical columns are written as an HDF5 group of `categories` + `codes`
+    // datasets rather than a plain dataset, so decode that shape first
+    let values = if let Ok(cat_group) = file.group(&path) {
+        read_categorical_column(&cat_group, &path)?
+    } else {
+        let dataset = file
+            .dataset(&path)
+            .map_err(|_| anyhow!("Requested {} column '{}' not found", group, col))?;
+
+        use TypeDescriptor as TD;
+        match dataset.dtype()?.to_descriptor()? {
+            TD::Integer(IntSize::U1) => dataset.read_1d::<i8>()?.iter().map(ToString::to_string).collect(),
+            TD::Integer(IntSize::U2) => dataset.read_1d::<i16>()?.iter().map(ToString::to_string).collect(),
+            TD::Integer(IntSize::U4) => dataset.read_1d::<i32>()?.iter().map(ToString::to_string).collect(),
+            TD::Integer(IntSize::U8) => dataset.read_1d::<i64>()?.iter().map(ToString::to_string).collect(),
+            TD::Unsigned(IntSize::U1) => dataset.read_1d::<u8>()?.iter().map(ToString::to_string).collect(),
+            TD::Unsigned(IntSize::U2) => dataset.read_1d::<u16>()?.iter().map(ToString::to_string).collect(),
+            TD::Unsigned(IntSize::U4) => dataset.read_1d::<u32>()?.iter().map(ToString::to_string).collect(),
+            TD::Unsigned(IntSize::U8) => dataset.read_1d::<u64>()?.iter().map(ToString::to_string).collect(),
+            TD::Float(FloatSize::U4) => dataset.read_1d::<f32>()?.iter().map(ToString::to_string).collect(),
+            TD::Float(FloatSize::U8) => dataset.read_1d::<f64>()?.iter().map(ToString::to_string).collect(),
+            TD::VarLenUnicode => dataset
+                .read_1d::<VarLenUnicode>()?
+                .iter()
+                .map(|v| v.as_str().to_owned())
+                .collect(),
+            _ => {
+                return Err(anyhow!(
+                    "Unsupported dtype for annotation column '{}'\nSupported dtypes: i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, str",
+                    path
+                ))
+            }
+        }
+    };
+
+    if values.len() != expected_len {
+        return Err(anyhow!(
+            "Annotation column '{}' has length {}, expected {}",
+            path,
+            values.len(),
+            expected_len
+        ));
+    }
+
+    Ok(values)
+}
+
+/// decode a pandas categorical column (an HDF5 group of `categories` + `codes`) into its string
+/// labels, mapping a negative code (pandas' NaN sentinel) to an empty string
+fn read_categorical_column(cat_group: &hdf5::Group, path: &str) -> anyhow::Result<Vec<String>> {
+    let categories = cat_group
+        .dataset("categories")
+        .map_err(|_| anyhow!("Categorical column '{}' is missing its categories dataset", path))?
+        .read_1d::<VarLenUnicode>()?;
+    let codes_dataset = cat_group
+        .dataset("codes")
+        .map_err(|_| anyhow!("Categorical column '{}' is missing its codes dataset", path))?;
+
+    use TypeDescriptor as TD;
+    let codes: Vec<i64> = match codes_dataset.dtype()?.to_descriptor()? {
+        TD::Integer(IntSize::U1) => codes_dataset.read_1d::<i8>()?.iter().map(|&v| v as i64).collect(),
+        TD::Integer(IntSize::U2) => codes_dataset.read_1d::<i16>()?.iter().map(|&v| v as i64).collect(),
+        TD::Integer(IntSize::U4) => codes_dataset.read_1d::<i32>()?.iter().map(|&v| v as i64).collect(),
+        TD::Integer(IntSize::U8) => codes_dataset.read_1d::<i64>()?.to_vec(),
+        _ => {
+            return Err(anyhow!(
+                "Unsupported codes dtype for categorical column '{}'",
+                path
+            ))
+        }
+    };
+
+    codes
+        .into_iter()
+        .map(|code| {
+            if code < 0 {
+                Ok(String::new())
+            } else {
+                categories
+                    .get(code as usize)
+                    .map(|c| c.as_str().to_owned())
+                    .ok_or_else(|| {
+                        anyhow!("Categorical column '{}' has an out-of-range code {}", path, code)
+                    })
+            }
+        })
+        .collect()
+}
+
+/// read an obs/var dataframe column from the AnnData object, stringifying its values (via
+/// pandas' `astype(str)`) so string and numeric annotation columns can be joined the same way.
+/// Errors if the column's length doesn't match `expected_len` (the axis it's being joined
+/// against), so a short column can't silently produce ragged CSV rows
+pub(crate) fn read_py_annotation_column(
+    frame: &PyAny,
+    col: &str,
+    expected_len: usize,
+) -> anyhow::Result<Vec<String>> {
+    let series = frame
+        .get_item(col)
+        .map_err(|_| anyhow!("Requested column '{}' not found", col))?;
+    let str_series = series.call_method1("astype", ("str",))?;
+    let values: Vec<String> = str_series.extract()?;
+
+    if values.len() != expected_len {
+        return Err(anyhow!(
+            "Annotation column '{}' has length {}, expected {}",
+            col,
+            values.len(),
+            expected_len
+        ));
+    }
+
+    Ok(values)
+}
+
+/// transpose a set of annotation columns (one Vec<String> per requested column, aligned by
+/// position with the rows) into one Vec<String> of values per row, in request order
+pub(crate) fn transpose_annotations(columns: &[Vec<String>], n_rows: usize) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![Vec::with_capacity(columns.len()); n_rows];
+    for column in columns {
+        for (row, value) in rows.iter_mut().zip(column.iter()) {
+            row.push(value.clone());
+        }
+    }
+    rows
+}
+
+/// write the CSV header: any requested column-axis annotations first, as extra header rows with
+/// the annotation's name in the row-name column and blanks under the row-axis annotation columns,
+/// followed by the row name column, the requested row-axis annotation column names, and the var-
+/// or obs-name header
+pub(crate) fn write_csv_header<W: std::io::Write>(
+    writer: &mut csv::Writer<W>,
+    first_col: &str,
+    row_annotation_names: &[String],
+    header: &[String],
+    col_annotation_names: &[String],
+    col_annotations: &[Vec<String>],
+) -> anyhow::Result<()> {
+    let n_leading = 1 + row_annotation_names.len();
+    for (name, values) in col_annotation_names.iter().zip(col_annotations.iter()) {
+        let mut record: Vec<&str> = vec![""; n_leading];
+        record[0] = name.as_str();
+        record.extend(values.iter().map(String::as_str));
+        writer.write_record(&record)?;
+    }
+
+    writer.write_field(first_col)?;
+    for name in row_annotation_names {
+        writer.write_field(name)?;
+    }
+    writer.write_record(header)?;
+
+    Ok(())
+}
+
+/// a Parquet or Arrow IPC file handle that RecordBatches can be appended to one block at a time
+enum ArrowSink {
+    Parquet(ArrowWriter<File>),
+    Ipc(IpcWriter<File>),
+}
+
+impl ArrowSink {
+    fn create(format: OutputFormat, schema: Arc<Schema>, outfile: &PathBuf) -> anyhow::Result<Self> {
+        let file = File::create(outfile)?;
+        match format {
+            OutputFormat::Parquet => Ok(ArrowSink::Parquet(ArrowWriter::try_new(file, schema, None)?)),
+            OutputFormat::Ipc => Ok(ArrowSink::Ipc(IpcWriter::try_new(file, &schema)?)),
+            _ => unreachable!("ArrowSink::create called with non-columnar OutputFormat"),
+        }
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> anyhow::Result<()> {
+        match self {
+            ArrowSink::Parquet(writer) => writer.write(batch)?,
+            ArrowSink::Ipc(writer) => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            ArrowSink::Parquet(mut writer) => writer.close().map(|_| ())?,
+            ArrowSink::Ipc(mut writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// write a counts matrix out as a Parquet or Arrow IPC file, one Arrow array per var- or
+/// obs-name column plus a leading row-name column. Rows are densified and written `block_size`
+/// at a time (the same block-at-a-time approach `stream_var_names_csv` uses for CSV) so peak
+/// memory holds one block of dense rows rather than the whole densified matrix at once
+pub(crate) fn write_arrow_batches<T: ArrayDtype>(
+    format: OutputFormat,
+    first_col: &str,
+    header: &[String],
+    row_names: &[String],
+    counts_mtx: &sprs::CsMatView<T>,
+    outfile: &PathBuf,
+    block_size: usize,
+) -> anyhow::Result<()> {
+    if block_size == 0 {
+        return Err(anyhow!("--block-size must be greater than 0"));
+    }
+
+    let mut fields = vec![Field::new(first_col, DataType::Utf8, false)];
+    for name in header {
+        fields.push(Field::new(name, T::arrow_data_type(), false));
+    }
+    let schema = Arc::new(Schema::new(fields));
+
+    info!("Writing {}", outfile.display());
+    let mut sink = ArrowSink::create(format, schema.clone(), outfile)?;
+
+    let n_rows = row_names.len();
+    let n_cols = header.len();
+    let mut row_iter = counts_mtx.outer_iterator();
+    let bar = create_progress_bar(n_rows);
+    let mut r0 = 0;
+    while r0 < n_rows {
+        let r1 = r0.saturating_add(block_size).min(n_rows);
+        let mut columns: Vec<Vec<T>> = vec![vec![T::default(); r1 - r0]; n_cols];
+        for local_idx in 0..(r1 - r0) {
+            let row = row_iter
+                .next()
+                .ok_or_else(|| anyhow!("row iterator ended before all rows were written"))?;
+            for (col_idx, value) in RowValIter::new(&row).enumerate() {
+                columns[col_idx][local_idx] = value;
+            }
+        }
+
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(StringArray::from(
+            row_names[r0..r1]
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+        ))];
+        for column in columns {
+            arrays.push(T::to_arrow_array(column));
+        }
+        let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+        sink.write(&batch)?;
+
+        bar.inc((r1 - r0) as u64);
+        r0 = r1;
+    }
+    bar.finish();
+    sink.finish()?;
+
+    info!("Done writing {}", outfile.display());
+
+    Ok(())
+}
+
+/// derive a sidecar file path by appending `suffix` to the full output path, e.g.
+/// `out.mtx` + `.rownames.txt` -> `out.mtx.rownames.txt`
+fn sidecar_path(outfile: &PathBuf, suffix: &str) -> PathBuf {
+    let mut path = outfile.clone().into_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// write only the nonzero entries of a counts matrix as a long-format CSV of
+/// row name/col name/value, skipping RowValIter's dense zero-fill
+pub(crate) fn write_triplet_csv<T: ArrayDtype>(
+    counts_mtx: &sprs::CsMatView<T>,
+    first_col: &str,
+    second_col: &str,
+    header: &[String],
+    row_names: &[String],
+    csv_opts: CsvOpts,
+    outfile: &PathBuf,
+) -> anyhow::Result<()> {
+    info!("Writing {}", outfile.display());
+    let mut writer = build_csv_writer_builder(csv_opts).from_path(outfile)?;
+    writer.write_record(&[first_col, second_col, "value"])?;
+
+    for (row, row_name) in counts_mtx
+        .outer_iterator()
+        .zip(row_names.iter())
+        .progress_with(create_progress_bar(row_names.len()))
+    {
+        for (&col_idx, value) in row.indices().iter().zip(row.data().iter()) {
+            writer.write_record(&[row_name.as_str(), header[col_idx].as_str(), &value.to_string()])?;
+        }
+    }
+
+    info!("Done writing {}", outfile.display());
+
+    Ok(())
+}
+
+/// write only the nonzero entries of a counts matrix as a MatrixMarket coordinate file, plus a
+/// row-names and a col-names sidecar file next to it (MatrixMarket has no room for string labels)
+pub(crate) fn write_mtx<T: ArrayDtype>(
+    counts_mtx: &sprs::CsMatView<T>,
+    header: &[String],
+    row_names: &[String],
+    outfile: &PathBuf,
+) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    info!("Writing {}", outfile.display());
+    let mut file = File::create(outfile)?;
+    writeln!(
+        file,
+        "%%MatrixMarket matrix coordinate {} general",
+        T::mtx_field()
+    )?;
+    writeln!(file, "{} {} {}", row_names.len(), header.len(), counts_mtx.nnz())?;
+    for (row_idx, row) in counts_mtx
+        .outer_iterator()
+        .enumerate()
+        .progress_with(create_progress_bar(row_names.len()))
+    {
+        for (&col_idx, value) in row.indices().iter().zip(row.data().iter()) {
+            writeln!(file, "{} {} {}", row_idx + 1, col_idx + 1, value)?;
+        }
+    }
+
+    let rownames_path = sidecar_path(outfile, ".rownames.txt");
+    let mut rownames_file = File::create(&rownames_path)?;
+    for name in row_names {
+        writeln!(rownames_file, "{}", name)?;
+    }
+
+    let colnames_path = sidecar_path(outfile, ".colnames.txt");
+    let mut colnames_file = File::create(&colnames_path)?;
+    for name in header {
+        writeln!(colnames_file, "{}", name)?;
+    }
+
+    info!(
+        "Done writing {}, {}, {}",
+        outfile.display(),
+        rownames_path.display(),
+        colnames_path.display()
+    );
+
+    Ok(())
+}
+
+/// write counts matrix to csv, joining in any requested obs/var annotation columns from `adata`
 fn arrays_to_csv<T: ArrayDtype>(
+    adata: &PyAny,
     args: LibOpts,
     data: Vec<T>,
     indptr: Vec<usize>,
@@ -174,48 +779,98 @@ fn arrays_to_csv<T: ArrayDtype>(
     obs_vec: Vec<String>,
     var_vec: Vec<String>,
 ) -> anyhow::Result<()> {
-    // get delimiter from args.delimiter
-    let delimiter = match args.delimiter {
-        Delimiter::Comma => b',',
-        Delimiter::Tab => b'\t',
-        Delimiter::Colon => b':',
-        Delimiter::Pipe => b'|',
-        Delimiter::Semicolon => b';',
-    };
+    // obs/var annotation columns are only joined into the row-major CSV writer; the columnar and
+    // sparse-triplet writers below have no place to put them, so reject the combination loudly
+    // instead of silently dropping the requested columns
+    if !matches!(args.format, OutputFormat::Csv) && !(args.obs_cols.is_empty() && args.var_cols.is_empty())
+    {
+        return Err(anyhow!(
+            "--obs-cols/--var-cols are only supported with --format csv"
+        ));
+    }
 
     // construct sparse matrix
     let mut counts_mtx = CsMatBase::try_new((obs_vec.len(), var_vec.len()), indptr, indices, data)?;
 
     // transpose matrix if needed based on column orientation specified
-    let (header, first_col, row_names) = match args.column_orient {
+    let (header, first_col, second_col, row_names, row_cols, col_cols) = match args.column_orient {
         Orient::ObsNames => {
             counts_mtx.transpose_mut();
             counts_mtx = counts_mtx.to_csr();
-            (obs_vec, "gene", var_vec)
+            (obs_vec, "gene", "cell", var_vec, &args.var_cols, &args.obs_cols)
         }
-        Orient::VarNames => (var_vec, "cell", obs_vec),
+        Orient::VarNames => (var_vec, "cell", "gene", obs_vec, &args.obs_cols, &args.var_cols),
     };
 
+    match args.format {
+        OutputFormat::Parquet | OutputFormat::Ipc => {
+            return write_arrow_batches(
+                args.format,
+                first_col,
+                &header,
+                &row_names,
+                &counts_mtx.view(),
+                &args.outfile,
+                DEFAULT_ARROW_BLOCK_SIZE,
+            );
+        }
+        OutputFormat::Triplet => {
+            return write_triplet_csv(
+                &counts_mtx.view(),
+                first_col,
+                second_col,
+                &header,
+                &row_names,
+                args.csv,
+                &args.outfile,
+            );
+        }
+        OutputFormat::Mtx => {
+            return write_mtx(&counts_mtx.view(), &header, &row_names, &args.outfile);
+        }
+        OutputFormat::Csv => {}
+    }
+
+    // the row-axis frame supplies the extra leading columns, the column-axis frame supplies the
+    // extra header rows
+    let (row_frame, col_frame) = match args.column_orient {
+        Orient::ObsNames => (adata.getattr("var")?, adata.getattr("obs")?),
+        Orient::VarNames => (adata.getattr("obs")?, adata.getattr("var")?),
+    };
+    let row_annotation_columns = row_cols
+        .iter()
+        .map(|col| read_py_annotation_column(row_frame, col, row_names.len()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let col_annotation_columns = col_cols
+        .iter()
+        .map(|col| read_py_annotation_column(col_frame, col, header.len()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let row_annotations = transpose_annotations(&row_annotation_columns, row_names.len());
+
     // open CSV file
     info!("Writing {}", args.outfile.display());
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(false)
-        .delimiter(delimiter)
-        .from_path(args.outfile.clone())?;
+    let mut writer = build_csv_writer_builder(args.csv).from_path(args.outfile.clone())?;
 
-    // write the column names
-    writer.write_field(first_col)?;
-    writer.write_record(header)?;
+    write_csv_header(
+        &mut writer,
+        first_col,
+        row_cols,
+        &header,
+        col_cols,
+        &col_annotation_columns,
+    )?;
 
     // write the rows to the CSV file
     let row_iter = counts_mtx.outer_iterator();
-    for (row, row_name) in row_iter
+    for ((row, row_name), annotations) in row_iter
         .zip(row_names.iter())
+        .zip(row_annotations.iter())
         .progress_with(create_progress_bar(row_names.len()))
     {
         let row_val_iter = RowValIter::new(&row);
         writer.serialize(Row {
             name: row_name,
+            annotations,
             values: row_val_iter,
         })?;
     }
@@ -240,16 +895,84 @@ fn make_csv(adata: &PyAny, args: LibOpts) -> anyhow::Result<()> {
         .getattr("name")?
         .to_string();
     match data_dtype.as_str() {
-        "int8" => arrays_to_csv(args, data.extract::<Vec<i8>>()?, indptr, indices, obs_vec, var_vec),
-        "int16" => arrays_to_csv(args, data.extract::<Vec<i16>>()?, indptr, indices, obs_vec, var_vec),
-        "int32" => arrays_to_csv(args, data.extract::<Vec<i32>>()?, indptr, indices, obs_vec, var_vec),
-        "int64" => arrays_to_csv(args, data.extract::<Vec<i64>>()?, indptr, indices, obs_vec, var_vec),
-        "uint8" => arrays_to_csv(args, data.extract::<Vec<u8>>()?, indptr, indices, obs_vec, var_vec),
-        "uint16" => arrays_to_csv(args, data.extract::<Vec<u16>>()?, indptr, indices, obs_vec, var_vec),
-        "uint32" => arrays_to_csv(args, data.extract::<Vec<u32>>()?, indptr, indices, obs_vec, var_vec),
-        "uint64" => arrays_to_csv(args, data.extract::<Vec<u64>>()?, indptr, indices, obs_vec, var_vec),
-        "float32" => arrays_to_csv(args, data.extract::<Vec<f32>>()?, indptr, indices, obs_vec, var_vec),
-        "float64" => arrays_to_csv(args, data.extract::<Vec<f64>>()?, indptr, indices, obs_vec, var_vec),
+        "int8" => arrays_to_csv(adata, args, data.extract::<Vec<i8>>()?, indptr, indices, obs_vec, var_vec),
+        "int16" => arrays_to_csv(adata, args, data.extract::<Vec<i16>>()?, indptr, indices, obs_vec, var_vec),
+        "int32" => arrays_to_csv(adata, args, data.extract::<Vec<i32>>()?, indptr, indices, obs_vec, var_vec),
+        "int64" => arrays_to_csv(adata, args, data.extract::<Vec<i64>>()?, indptr, indices, obs_vec, var_vec),
+        "uint8" => arrays_to_csv(adata, args, data.extract::<Vec<u8>>()?, indptr, indices, obs_vec, var_vec),
+        "uint16" => arrays_to_csv(adata, args, data.extract::<Vec<u16>>()?, indptr, indices, obs_vec, var_vec),
+        "uint32" => arrays_to_csv(adata, args, data.extract::<Vec<u32>>()?, indptr, indices, obs_vec, var_vec),
+        "uint64" => arrays_to_csv(adata, args, data.extract::<Vec<u64>>()?, indptr, indices, obs_vec, var_vec),
+        "float32" => arrays_to_csv(adata, args, data.extract::<Vec<f32>>()?, indptr, indices, obs_vec, var_vec),
+        "float64" => arrays_to_csv(adata, args, data.extract::<Vec<f64>>()?, indptr, indices, obs_vec, var_vec),
         _ => Err(anyhow!("Invalid data type\nPossible data types: i8, i16, i32, i64, u8, u16, u32, u64, f32, f64")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpose_annotations_aligns_columns_to_rows() {
+        let columns = vec![
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            vec!["x".to_string(), "y".to_string(), "z".to_string()],
+        ];
+        let rows = transpose_annotations(&columns, 3);
+        assert_eq!(
+            rows,
+            vec![
+                vec!["a".to_string(), "x".to_string()],
+                vec!["b".to_string(), "y".to_string()],
+                vec!["c".to_string(), "z".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn transpose_annotations_handles_no_requested_columns() {
+        let rows = transpose_annotations(&[], 3);
+        assert_eq!(rows, vec![Vec::<String>::new(); 3]);
+    }
+
+    #[test]
+    fn parse_cols_trims_and_drops_empty_entries() {
+        assert_eq!(
+            parse_cols(" cell_type, batch ,,"),
+            vec!["cell_type".to_string(), "batch".to_string()]
+        );
+    }
+
+    #[test]
+    fn rebase_indptr_starts_each_block_at_zero() {
+        let indptr = vec![0, 2, 5, 9, 9, 12];
+        assert_eq!(rebase_indptr(&indptr, 1, 3), vec![0, 3, 7]);
+        assert_eq!(rebase_indptr(&indptr, 0, 5), vec![0, 2, 5, 9, 9, 12]);
+    }
+
+    #[test]
+    fn row_val_iter_fills_zeros_between_stored_values() {
+        let data = vec![10i32, 20];
+        let indices = vec![1usize, 3];
+        let row = sprs::CsVec::new(5, indices, data);
+        let values: Vec<i32> = RowValIter::new(&row.view()).collect();
+        assert_eq!(values, vec![0, 10, 0, 20, 0]);
+    }
+
+    #[test]
+    fn build_csv_writer_builder_applies_delimiter_and_terminator() {
+        let opts = CsvOpts {
+            delimiter: Delimiter::Pipe,
+            quote: b'"',
+            quote_style: QuoteStyleArg::Necessary,
+            escape_style: EscapeStyle::DoubleQuote,
+            escape: b'\\',
+            terminator: LineTerminator::Windows,
+        };
+        let mut writer = build_csv_writer_builder(opts).from_writer(Vec::new());
+        writer.write_record(&["a", "b"]).unwrap();
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes, b"a|b\r\n");
+    }
+}
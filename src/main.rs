@@ -1,6 +1,5 @@
 use anyhow::anyhow;
 use clap::Clap;
-use csv;
 use hdf5::types::*;
 use indicatif::ProgressIterator;
 mod lib;
@@ -42,26 +41,196 @@ struct MainOpts {
         default_value = "out.csv"
     )]
     outfile: PathBuf,
+    #[clap(
+        long,
+        arg_enum,
+        about = "output file format: a row-major CSV, a column-major Parquet or Arrow IPC file, \
+                 or a sparse triplet CSV or MatrixMarket file that skips zero entries",
+        default_value = "csv"
+    )]
+    format: OutputFormat,
+    #[clap(
+        long,
+        about = "number of rows to read and write per block when streaming a var-names CSV, and \
+                 number of rows per record batch for Parquet/IPC output",
+        default_value = "100000"
+    )]
+    block_size: usize,
+    #[clap(long, about = "character used to quote CSV fields", default_value = "\"")]
+    quote: char,
+    #[clap(
+        long,
+        arg_enum,
+        about = "when to quote CSV fields: always, necessary, non-numeric, or never",
+        default_value = "necessary"
+    )]
+    quote_style: QuoteStyleArg,
+    #[clap(
+        long,
+        arg_enum,
+        about = "how a quote character inside a field is escaped: by doubling it, or with a backslash escape character",
+        default_value = "double-quote"
+    )]
+    escape_style: EscapeStyle,
+    #[clap(
+        long,
+        about = "escape character used when escape-style is backslash",
+        default_value = "\\"
+    )]
+    escape: char,
+    #[clap(
+        long,
+        arg_enum,
+        about = "line terminator for the CSV file: unix (\\n) or windows (\\r\\n)",
+        default_value = "unix"
+    )]
+    line_terminator: LineTerminator,
+    #[clap(
+        long,
+        about = "comma-separated obs columns to join in: extra leading row columns when \
+                 orientation is var-names, extra header rows when orientation is obs-names",
+        default_value = ""
+    )]
+    obs_cols: String,
+    #[clap(
+        long,
+        about = "comma-separated var columns to join in: extra leading row columns when \
+                 orientation is obs-names, extra header rows when orientation is var-names",
+        default_value = ""
+    )]
+    var_cols: String,
+}
+
+impl MainOpts {
+    fn csv_opts(&self) -> anyhow::Result<CsvOpts> {
+        Ok(CsvOpts {
+            delimiter: self.delimiter,
+            quote: single_byte_char(self.quote, "quote")?,
+            quote_style: self.quote_style,
+            escape_style: self.escape_style,
+            escape: single_byte_char(self.escape, "escape")?,
+            terminator: self.line_terminator,
+        })
+    }
 }
 
-/// write counts matrix to csv
-fn file_to_csv<T: ArrayDtype>(
-    file: hdf5::File,
-    data: Vec<T>,
-    args: MainOpts,
+/// validate that a CLI char argument (e.g. `--quote`/`--escape`) fits in a single byte, since
+/// csv::WriterBuilder's quote/escape take a u8, not a char
+fn single_byte_char(c: char, what: &str) -> anyhow::Result<u8> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(anyhow!(
+            "Invalid {}: {}\nExpected a single-byte (ASCII) character",
+            what,
+            c
+        ))
+    }
+}
+
+/// stream a var-names oriented CSV out in blocks of `block_size` obs rows at a time. Only the
+/// nonzero slice of X/data and X/indices needed for each block is read from the HDF5 file (via a
+/// hyperslab selection on `[indptr[r0], indptr[r1])`), so peak memory is bounded by one block
+/// instead of the whole matrix. This only works for the var-names orientation, where CSR rows
+/// are already obs and no transpose is required.
+fn stream_var_names_csv<T: ArrayDtype>(
+    file: &hdf5::File,
+    indptr: &[usize],
+    var_vec: &[String],
+    obs_vec: &[String],
+    csv_opts: CsvOpts,
+    outfile: &PathBuf,
+    block_size: usize,
+    obs_cols: &[String],
+    var_cols: &[String],
 ) -> anyhow::Result<()> {
-    // get delimiter from args.delimiter
-    let delimiter = match args.delimiter {
-        Delimiter::Comma => b',',
-        Delimiter::Tab => b'\t',
-        Delimiter::Colon => b':',
-        Delimiter::Pipe => b'|',
-        Delimiter::Semicolon => b';',
-    };
+    if block_size == 0 {
+        return Err(anyhow!("--block-size must be greater than 0"));
+    }
+
+    let n_obs = obs_vec.len();
+    let n_var = var_vec.len();
+    let data_ds = file.dataset("X/data")?;
+    let indices_ds = file.dataset("X/indices")?;
+
+    // obs are rows in this orientation, so obs-cols become extra leading row columns and
+    // var-cols become extra header rows
+    let obs_annotation_columns = obs_cols
+        .iter()
+        .map(|col| read_annotation_column(file, "obs", col, n_obs))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let var_annotation_columns = var_cols
+        .iter()
+        .map(|col| read_annotation_column(file, "var", col, n_var))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let row_annotations = transpose_annotations(&obs_annotation_columns, n_obs);
+
+    info!("Writing {}", outfile.display());
+    let mut writer = build_csv_writer_builder(csv_opts).from_path(outfile)?;
+
+    write_csv_header(
+        &mut writer,
+        "cell",
+        obs_cols,
+        var_vec,
+        var_cols,
+        &var_annotation_columns,
+    )?;
+
+    let bar = create_progress_bar(n_obs);
+    let mut r0 = 0;
+    while r0 < n_obs {
+        let r1 = r0.saturating_add(block_size).min(n_obs);
+        let lo = indptr[r0];
+        let hi = indptr[r1];
+
+        let block_data = data_ds.read_slice_1d::<T, _>(lo..hi)?.to_vec();
+        let block_indices = indices_ds.read_slice_1d::<usize, _>(lo..hi)?.to_vec();
+        let block_indptr = rebase_indptr(indptr, r0, r1);
+
+        let block_mtx =
+            CsMatBase::try_new((r1 - r0, n_var), block_indptr, block_indices, block_data)?;
+        for ((row, row_name), annotations) in block_mtx
+            .outer_iterator()
+            .zip(obs_vec[r0..r1].iter())
+            .zip(row_annotations[r0..r1].iter())
+        {
+            writer.serialize(Row {
+                name: row_name.as_str(),
+                annotations,
+                values: RowValIter::new(&row),
+            })?;
+        }
+
+        bar.inc((r1 - r0) as u64);
+        r0 = r1;
+    }
+    bar.finish();
+
+    info!("Done writing {}", outfile.display());
+
+    Ok(())
+}
+
+/// write counts matrix to csv, streaming row blocks straight from the HDF5 file when the
+/// var-names/CSV combination allows it; other orientation/format combinations fall back to
+/// loading the whole matrix into memory
+fn file_to_csv<T: ArrayDtype>(file: hdf5::File, args: MainOpts) -> anyhow::Result<()> {
+    let csv_opts = args.csv_opts()?;
+    let obs_cols = parse_cols(&args.obs_cols);
+    let var_cols = parse_cols(&args.var_cols);
+
+    // obs/var annotation columns are only joined into the row-major CSV writer; the columnar and
+    // sparse-triplet writers below have no place to put them, so reject the combination loudly
+    // instead of silently dropping the requested columns
+    if !matches!(args.format, OutputFormat::Csv) && !(obs_cols.is_empty() && var_cols.is_empty()) {
+        return Err(anyhow!(
+            "--obs-cols/--var-cols are only supported with --format csv"
+        ));
+    }
 
-    // get indptr and indices arrays for creating sparse matrix
+    // indptr is only n_obs + 1 integers, so it is always read in full
     let indptr = file.dataset("X/indptr")?.read_1d::<usize>()?.to_vec();
-    let indices = file.dataset("X/indices")?.read_1d::<usize>()?.to_vec();
 
     // get index column name of var and obs dataframes
     let var_index_name = file
@@ -74,48 +243,120 @@ fn file_to_csv<T: ArrayDtype>(
         .read_scalar::<VarLenUnicode>()?;
 
     // read var and obs index columns
-    let var_vec = file
+    let var_vec: Vec<String> = file
         .dataset(&format!("var/{}", var_index_name.as_str()))?
         .read_1d::<VarLenUnicode>()?
-        .to_vec();
-    let obs_vec = file
+        .iter()
+        .map(|n| n.as_str().to_owned())
+        .collect();
+    let obs_vec: Vec<String> = file
         .dataset(&format!("obs/{}", obs_index_name.as_str()))?
         .read_1d::<VarLenUnicode>()?
-        .to_vec();
+        .iter()
+        .map(|n| n.as_str().to_owned())
+        .collect();
+
+    if let (Orient::VarNames, OutputFormat::Csv) = (args.column_orient, args.format) {
+        return stream_var_names_csv::<T>(
+            &file,
+            &indptr,
+            &var_vec,
+            &obs_vec,
+            csv_opts,
+            &args.outfile,
+            args.block_size,
+            &obs_cols,
+            &var_cols,
+        );
+    }
+
+    // indices and data are only read in full for combinations streaming doesn't support
+    let indices = file.dataset("X/indices")?.read_1d::<usize>()?.to_vec();
+    let data = file.dataset("X/data")?.read_1d::<T>()?.to_vec();
 
     // construct sparse matrix
     let mut counts_mtx = CsMatBase::try_new((obs_vec.len(), var_vec.len()), indptr, indices, data)?;
 
-    // transpose matrix if needed based on column orientation specified
-    let (header, first_col, row_names) = match args.column_orient {
+    // transpose matrix if needed based on column orientation specified; the row-axis annotation
+    // columns become extra leading row columns, the column-axis ones become extra header rows
+    let (header, first_col, second_col, row_names, row_cols, col_cols) = match args.column_orient {
         Orient::ObsNames => {
             counts_mtx.transpose_mut();
             counts_mtx = counts_mtx.to_csr();
-            (obs_vec, "gene", var_vec)
+            (obs_vec, "gene", "cell", var_vec, &var_cols, &obs_cols)
+        }
+        Orient::VarNames => (var_vec, "cell", "gene", obs_vec, &obs_cols, &var_cols),
+    };
+
+    match args.format {
+        OutputFormat::Parquet | OutputFormat::Ipc => {
+            return write_arrow_batches(
+                args.format,
+                first_col,
+                &header,
+                &row_names,
+                &counts_mtx.view(),
+                &args.outfile,
+                args.block_size,
+            );
         }
-        Orient::VarNames => (var_vec, "cell", obs_vec),
+        OutputFormat::Triplet => {
+            return write_triplet_csv(
+                &counts_mtx.view(),
+                first_col,
+                second_col,
+                &header,
+                &row_names,
+                csv_opts,
+                &args.outfile,
+            );
+        }
+        OutputFormat::Mtx => {
+            return write_mtx(&counts_mtx.view(), &header, &row_names, &args.outfile);
+        }
+        OutputFormat::Csv => {}
+    }
+
+    // the row-axis group supplies the extra leading columns, the column-axis group supplies the
+    // extra header rows
+    let (row_group, col_group) = match args.column_orient {
+        Orient::ObsNames => ("var", "obs"),
+        Orient::VarNames => ("obs", "var"),
     };
+    let row_annotation_columns = row_cols
+        .iter()
+        .map(|col| read_annotation_column(&file, row_group, col, row_names.len()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let col_annotation_columns = col_cols
+        .iter()
+        .map(|col| read_annotation_column(&file, col_group, col, header.len()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let row_annotations = transpose_annotations(&row_annotation_columns, row_names.len());
 
     // open CSV file
     info!("Writing {}", args.outfile.display());
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(false)
-        .delimiter(delimiter)
-        .from_path(args.outfile.clone())?;
+    let mut writer = build_csv_writer_builder(csv_opts).from_path(args.outfile.clone())?;
 
-    // write the column names
-    writer.write_field(first_col)?;
-    writer.write_record(header)?;
+    write_csv_header(
+        &mut writer,
+        first_col,
+        row_cols,
+        &header,
+        col_cols,
+        &col_annotation_columns,
+    )?;
 
     // write the rows to the CSV file
     let row_iter = counts_mtx.outer_iterator();
-    for (row, row_name) in row_iter
+    for ((row, row_name), annotations) in row_iter
         .zip(row_names.iter())
+        .zip(row_annotations.iter())
         .progress_with(create_progress_bar(row_names.len()))
     {
         let row_val_iter = RowValIter::new(&row);
         writer.serialize(Row {
             name: row_name,
+            annotations,
             values: row_val_iter,
         })?;
     }
@@ -134,22 +375,22 @@ fn main() -> anyhow::Result<()> {
     // read file and determine counts matrix data type
     info!("Reading H5 file");
     let file = hdf5::File::open(&args.h5_file)?;
-    let data = file.dataset("X/data")?;
-    let data_dtype = data.dtype()?.to_descriptor()?;
+    let data_dtype = file.dataset("X/data")?.dtype()?.to_descriptor()?;
 
-    // call file_to_csv based on corresponding matrix data type
+    // call file_to_csv based on corresponding matrix data type; the data array itself is read
+    // by file_to_csv, either in full or streamed in blocks depending on args
     use TypeDescriptor as TD;
     match data_dtype {
-        TD::Integer(IntSize::U1) => file_to_csv(file, data.read_1d::<i8>()?.to_vec(), args),
-        TD::Integer(IntSize::U2) => file_to_csv(file, data.read_1d::<i16>()?.to_vec(), args),
-        TD::Integer(IntSize::U4) => file_to_csv(file, data.read_1d::<i32>()?.to_vec(), args),
-        TD::Integer(IntSize::U8) => file_to_csv(file, data.read_1d::<i64>()?.to_vec(), args),
-        TD::Unsigned(IntSize::U1) => file_to_csv(file, data.read_1d::<u8>()?.to_vec(), args),
-        TD::Unsigned(IntSize::U2) => file_to_csv(file, data.read_1d::<u16>()?.to_vec(), args),
-        TD::Unsigned(IntSize::U4) => file_to_csv(file, data.read_1d::<u32>()?.to_vec(), args),
-        TD::Unsigned(IntSize::U8) => file_to_csv(file, data.read_1d::<u64>()?.to_vec(), args),
-        TD::Float(FloatSize::U4) => file_to_csv(file, data.read_1d::<f32>()?.to_vec(), args),
-        TD::Float(FloatSize::U8) => file_to_csv(file, data.read_1d::<f64>()?.to_vec(), args),
+        TD::Integer(IntSize::U1) => file_to_csv::<i8>(file, args),
+        TD::Integer(IntSize::U2) => file_to_csv::<i16>(file, args),
+        TD::Integer(IntSize::U4) => file_to_csv::<i32>(file, args),
+        TD::Integer(IntSize::U8) => file_to_csv::<i64>(file, args),
+        TD::Unsigned(IntSize::U1) => file_to_csv::<u8>(file, args),
+        TD::Unsigned(IntSize::U2) => file_to_csv::<u16>(file, args),
+        TD::Unsigned(IntSize::U4) => file_to_csv::<u32>(file, args),
+        TD::Unsigned(IntSize::U8) => file_to_csv::<u64>(file, args),
+        TD::Float(FloatSize::U4) => file_to_csv::<f32>(file, args),
+        TD::Float(FloatSize::U8) => file_to_csv::<f64>(file, args),
         _ => Err(anyhow!("Invalid data type\nPossible data types: i8, i16, i32, i64, u8, u16, u32, u64, f32, f64")),
     }
 }